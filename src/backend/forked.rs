@@ -0,0 +1,240 @@
+//! # Forked backend
+//!
+//! A `Backend` that overlays a local state cache on top of a remote chain, fetching
+//! whatever it is missing from a JSON-RPC endpoint pinned to a fork block.
+
+use super::memory::{MemoryAccount, MemoryVicinity, TxReceipt};
+use super::{Backend, Basic, Log};
+use crate::error::BackendError;
+use primitive_types::{H160, H256, U256};
+use sha3::{Digest, Keccak256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// A `Backend` that reads through to a JSON-RPC endpoint, pinned at `fork_block`,
+/// whenever a queried slot is missing from its local overlay, caching the result so
+/// every later read of the same slot is free.
+pub struct ForkedBackend<'vicinity> {
+    vicinity: &'vicinity MemoryVicinity,
+    endpoint: String,
+    fork_block: U256,
+    state: RefCell<BTreeMap<H160, MemoryAccount>>,
+}
+
+impl<'vicinity> ForkedBackend<'vicinity> {
+    /// Create a backend that forks `endpoint` at `fork_block`, with nothing cached
+    /// locally yet.
+    pub fn new(
+        vicinity: &'vicinity MemoryVicinity,
+        endpoint: impl Into<String>,
+        fork_block: U256,
+    ) -> Self {
+        Self {
+            vicinity,
+            endpoint: endpoint.into(),
+            fork_block,
+            state: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value, BackendError> {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let response: serde_json::Value = ureq::post(&self.endpoint)
+            .send_json(body)
+            .map_err(|e| BackendError::Rpc(e.to_string()))?
+            .into_json()
+            .map_err(|e| BackendError::Decode(e.to_string()))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(BackendError::Rpc(error.to_string()));
+        }
+
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| BackendError::Decode("JSON-RPC response missing `result`".into()))
+    }
+
+    fn fork_block_tag(&self) -> String {
+        format!("0x{:x}", self.fork_block)
+    }
+
+    /// Fetch and cache `address`'s account, or return the already-cached one.
+    fn account(&self, address: H160) -> Result<MemoryAccount, BackendError> {
+        if let Some(account) = self.state.borrow().get(&address) {
+            return Ok(account.clone());
+        }
+
+        let block = self.fork_block_tag();
+        let address_hex = format!("{:?}", address);
+
+        let balance = parse_u256(&self.call("eth_getBalance", serde_json::json!([address_hex, block]))?)?;
+        let nonce = parse_u256(&self.call(
+            "eth_getTransactionCount",
+            serde_json::json!([address_hex, self.fork_block_tag()]),
+        )?)?;
+        let code = parse_bytes(&self.call("eth_getCode", serde_json::json!([address_hex, self.fork_block_tag()]))?)?;
+
+        let account = MemoryAccount {
+            nonce,
+            balance,
+            storage: BTreeMap::new(),
+            code,
+            created: false,
+        };
+
+        self.state.borrow_mut().insert(address, account.clone());
+        Ok(account)
+    }
+
+    /// Fetch and cache a single storage slot, or return the already-cached value.
+    ///
+    /// Always fetches the full account first (via `account`), so a slot read that
+    /// arrives before any balance/nonce/code read for `address` doesn't plant a
+    /// zeroed stub in the cache that later shadows the real account.
+    fn storage_slot(&self, address: H160, index: H256) -> Result<H256, BackendError> {
+        self.account(address)?;
+
+        if let Some(value) = self
+            .state
+            .borrow()
+            .get(&address)
+            .and_then(|a| a.storage.get(&index))
+            .cloned()
+        {
+            return Ok(value);
+        }
+
+        let address_hex = format!("{:?}", address);
+        let index_hex = format!("{:?}", index);
+        let block = self.fork_block_tag();
+
+        let value = parse_h256(&self.call(
+            "eth_getStorageAt",
+            serde_json::json!([address_hex, index_hex, block]),
+        )?)?;
+
+        self.state
+            .borrow_mut()
+            .entry(address)
+            .or_insert_with(Default::default)
+            .storage
+            .insert(index, value);
+
+        Ok(value)
+    }
+}
+
+impl<'vicinity> Backend for ForkedBackend<'vicinity> {
+    fn gas_price(&self) -> U256 {
+        self.vicinity.gas_price
+    }
+
+    fn origin(&self) -> H160 {
+        self.vicinity.origin
+    }
+
+    fn block_hash(&self, number: U256) -> Result<H256, BackendError> {
+        let tag = format!("0x{:x}", number);
+        let block = self.call("eth_getBlockByNumber", serde_json::json!([tag, false]))?;
+        match block.get("hash") {
+            Some(hash) => parse_h256(hash),
+            None => Ok(H256::default()),
+        }
+    }
+
+    fn block_number(&self) -> U256 {
+        self.vicinity.block_number
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.vicinity.block_coinbase
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.vicinity.block_timestamp
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.vicinity.block_difficulty
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.vicinity.block_gas_limit
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.vicinity.chain_id
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        // `account` succeeds for essentially any syntactically valid address, since
+        // the RPC calls it makes simply return zero/empty for untouched accounts —
+        // success alone only tells us the endpoint is reachable. Check the fetched
+        // fields themselves to answer "has state": non-zero nonce or balance, or code.
+        match self.account(address) {
+            Ok(account) => {
+                account.nonce != U256::zero() || account.balance != U256::zero() || !account.code.is_empty()
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn basic(&self, address: H160) -> Result<Basic, BackendError> {
+        let account = self.account(address)?;
+        Ok(Basic {
+            balance: account.balance,
+            nonce: account.nonce,
+        })
+    }
+
+    fn code_hash(&self, address: H160) -> H256 {
+        self.account(address)
+            .map(|a| H256::from_slice(Keccak256::digest(&a.code).as_slice()))
+            .unwrap_or_else(|_| H256::from_slice(Keccak256::digest(&[]).as_slice()))
+    }
+
+    fn code_size(&self, address: H160) -> usize {
+        self.account(address).map(|a| a.code.len()).unwrap_or(0)
+    }
+
+    fn code(&self, address: H160) -> Result<Vec<u8>, BackendError> {
+        Ok(self.account(address)?.code)
+    }
+
+    fn storage(&self, address: H160, index: H256) -> Result<H256, BackendError> {
+        self.storage_slot(address, index)
+    }
+
+    fn tx_receipt(&self, _hash: H256) -> Result<TxReceipt, BackendError> {
+        Ok(TxReceipt::default())
+    }
+}
+
+fn parse_u256(value: &serde_json::Value) -> Result<U256, BackendError> {
+    let hex = value
+        .as_str()
+        .ok_or_else(|| BackendError::Decode("expected a hex-encoded quantity".into()))?;
+    U256::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|e| BackendError::Decode(e.to_string()))
+}
+
+fn parse_h256(value: &serde_json::Value) -> Result<H256, BackendError> {
+    let bytes = parse_bytes(value)?;
+    let mut buf = [0u8; 32];
+    let start = 32usize.saturating_sub(bytes.len());
+    buf[start..].copy_from_slice(&bytes[bytes.len().saturating_sub(32)..]);
+    Ok(H256::from(buf))
+}
+
+fn parse_bytes(value: &serde_json::Value) -> Result<Vec<u8>, BackendError> {
+    let hex = value
+        .as_str()
+        .ok_or_else(|| BackendError::Decode("expected a hex-encoded byte string".into()))?;
+    hex::decode(hex.trim_start_matches("0x")).map_err(|e| BackendError::Decode(e.to_string()))
+}
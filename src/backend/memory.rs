@@ -3,13 +3,19 @@
 //! Memory stuff
 
 use super::{Apply, ApplyBackend, Backend, Basic, Log};
+use crate::error::BackendError;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use primitive_types::{H160, H256, U256};
+use rlp::RlpStream;
 use sha3::{Digest, Keccak256};
 use std::collections::BTreeSet;
 
 /// Transaction receipt
+///
+/// Does not derive `Encode`/`Decode` itself even under `with-codec`: its `logs` field
+/// holds `Log`, which is defined outside this module and isn't guaranteed to derive
+/// them. `MemoryBackend::snapshot`/`restore` instead go through `EncodableTxReceipt`.
 #[derive(Clone, Default, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TxReceipt {
@@ -36,6 +42,7 @@ pub struct TxReceipt {
 /// Vivinity value of a memory backend.
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "with-codec", derive(parity_scale_codec::Encode, parity_scale_codec::Decode))]
 pub struct MemoryVicinity {
     /// Gas price.
     pub gas_price: U256,
@@ -60,6 +67,7 @@ pub struct MemoryVicinity {
 /// Account information of a memory backend.
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "with-codec", derive(parity_scale_codec::Encode, parity_scale_codec::Decode))]
 pub struct MemoryAccount {
     /// Account nonce.
     pub nonce: U256,
@@ -73,15 +81,152 @@ pub struct MemoryAccount {
     pub created: bool,
 }
 
+/// A SCALE-encodable mirror of `Log`, used by `MemoryBackend::snapshot`/`restore`
+/// since `Log` is defined outside this module and can't be relied on to derive
+/// `Encode`/`Decode` itself.
+#[cfg(feature = "with-codec")]
+#[derive(Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+struct EncodableLog {
+    address: H160,
+    topics: Vec<H256>,
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "with-codec")]
+impl From<&Log> for EncodableLog {
+    fn from(log: &Log) -> Self {
+        Self {
+            address: log.address,
+            topics: log.topics.clone(),
+            data: log.data.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "with-codec")]
+impl From<EncodableLog> for Log {
+    fn from(log: EncodableLog) -> Self {
+        Log {
+            address: log.address,
+            topics: log.topics,
+            data: log.data,
+        }
+    }
+}
+
+/// A SCALE-encodable mirror of `TxReceipt`, with `logs: Vec<EncodableLog>` in place of
+/// `Vec<Log>`, used by `MemoryBackend::snapshot`/`restore`.
+#[cfg(feature = "with-codec")]
+#[derive(parity_scale_codec::Encode, parity_scale_codec::Decode)]
+struct EncodableTxReceipt {
+    hash: H256,
+    caller: H160,
+    to: Option<H160>,
+    block_number: U256,
+    cumulative_gas_used: usize,
+    gas_used: usize,
+    contract_addresses: BTreeSet<H160>,
+    logs: Vec<EncodableLog>,
+    status: usize,
+}
+
+#[cfg(feature = "with-codec")]
+impl From<&TxReceipt> for EncodableTxReceipt {
+    fn from(rec: &TxReceipt) -> Self {
+        Self {
+            hash: rec.hash,
+            caller: rec.caller,
+            to: rec.to,
+            block_number: rec.block_number,
+            cumulative_gas_used: rec.cumulative_gas_used,
+            gas_used: rec.gas_used,
+            contract_addresses: rec.contract_addresses.clone(),
+            logs: rec.logs.iter().map(EncodableLog::from).collect(),
+            status: rec.status,
+        }
+    }
+}
+
+#[cfg(feature = "with-codec")]
+impl From<EncodableTxReceipt> for TxReceipt {
+    fn from(rec: EncodableTxReceipt) -> Self {
+        Self {
+            hash: rec.hash,
+            caller: rec.caller,
+            to: rec.to,
+            block_number: rec.block_number,
+            cumulative_gas_used: rec.cumulative_gas_used,
+            gas_used: rec.gas_used,
+            contract_addresses: rec.contract_addresses,
+            logs: rec.logs.into_iter().map(Log::from).collect(),
+            status: rec.status,
+        }
+    }
+}
+
+/// A single overlay layer of uncommitted state introduced by entering a call frame.
+///
+/// Layers stack on top of each other and, ultimately, on top of `MemoryBackend::state`.
+/// Reads walk the stack top-down so the most recently entered, still-open frame wins.
+#[derive(Clone, Debug, Default)]
+struct CheckpointLayer {
+    /// Per-account storage writes made while this layer was on top of the stack.
+    storage: BTreeMap<H160, BTreeMap<H256, H256>>,
+    /// Per-account balance writes made while this layer was on top of the stack.
+    balance: BTreeMap<H160, U256>,
+    /// Per-account nonce writes made while this layer was on top of the stack.
+    nonce: BTreeMap<H160, U256>,
+    /// Value of the refund counter at the moment this layer was pushed, so a revert
+    /// can restore it exactly.
+    refund_snapshot: i64,
+}
+
+/// The EIP-2200 / EIP-1283 classification of an `SSTORE`, used to price the opcode
+/// under net gas metering and to drive refund bookkeeping.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SstoreCost {
+    /// `current == new`: the slot is unchanged, priced as a no-op read.
+    NoOp,
+    /// `original == current` and `original` is zero: the slot is being set for the
+    /// first time this transaction, priced at the full `SSTORE_SET` cost.
+    InitialSet,
+    /// `original == current` and `original` is non-zero: an update of a non-zero
+    /// slot that has not been touched yet this transaction, priced at `SSTORE_RESET`.
+    CleanUpdate,
+    /// `original != current`: the slot was already touched this transaction, priced
+    /// at the cheap `SLOAD` cost with refunds settled against `original`.
+    DirtyUpdate,
+}
+
+/// Gas cost, in gas units, charged for each `SstoreCost` tier under net gas metering.
+const SSTORE_SET_GAS: u64 = 20_000;
+const SSTORE_RESET_GAS: u64 = 5_000;
+const SLOAD_GAS: u64 = 200;
+/// Refund granted for clearing a slot with a non-zero original value to zero, and
+/// withdrawn again if such a cleared slot is later set non-zero, per EIP-2200.
+const SSTORE_CLEARS_SCHEDULE: i64 = 15_000;
+
 /// Memory backend, storing all state values in a `BTreeMap` in memory.
 #[derive(Clone, Debug)]
 pub struct MemoryBackend<'vicinity> {
     vicinity: &'vicinity MemoryVicinity,
     state: BTreeMap<H160, MemoryAccount>,
     archive_state: BTreeMap<U256, BTreeMap<H160, MemoryAccount>>,
+    /// Per-address index over `archive_state`, rebuilt from it on `restore` and kept
+    /// in sync by `apply`, so `archive_account_at` can range-scan one address's own
+    /// history instead of every archived block.
+    archive_by_address: BTreeMap<H160, BTreeMap<U256, MemoryAccount>>,
     local_block_num: U256,
     logs: BTreeMap<U256, Vec<Log>>,
     tx_history: BTreeMap<H256, TxReceipt>,
+    /// Open call-frame overlays, pushed by `checkpoint` and resolved by
+    /// `revert_to_checkpoint` or `commit_checkpoint`.
+    checkpoints: Vec<CheckpointLayer>,
+    /// Value each touched `(address, index)` slot held at the start of the current
+    /// transaction, used to answer `original_storage`.
+    original_storage: BTreeMap<(H160, H256), H256>,
+    /// Accumulated gas refund for the current transaction.
+    refund_counter: i64,
 }
 
 impl<'vicinity> MemoryBackend<'vicinity> {
@@ -91,9 +236,13 @@ impl<'vicinity> MemoryBackend<'vicinity> {
             vicinity,
             state,
             archive_state: BTreeMap::new(),
+            archive_by_address: BTreeMap::new(),
             local_block_num: vicinity.block_number.clone(),
             logs: BTreeMap::new(),
             tx_history: BTreeMap::new(),
+            checkpoints: Vec::new(),
+            original_storage: BTreeMap::new(),
+            refund_counter: 0,
         }
     }
 
@@ -101,6 +250,400 @@ impl<'vicinity> MemoryBackend<'vicinity> {
     pub fn state(&self) -> &BTreeMap<H160, MemoryAccount> {
         &self.state
     }
+
+    /// Logs emitted while applying transactions at `block`, in emission order.
+    pub fn logs_at(&self, block: U256) -> &[Log] {
+        self.logs.get(&block).map(|l| l.as_slice()).unwrap_or(&[])
+    }
+
+    /// Clear per-transaction bookkeeping (the `original_storage` snapshot and the
+    /// refund counter). Call this before executing a new transaction.
+    pub fn reset_transaction(&mut self) {
+        self.original_storage.clear();
+        self.refund_counter = 0;
+    }
+
+    /// Current accumulated gas refund for the transaction in progress.
+    pub fn refund_counter(&self) -> i64 {
+        self.refund_counter
+    }
+
+    /// Push a new overlay layer onto the checkpoint stack, for entering a call frame.
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(CheckpointLayer {
+            refund_snapshot: self.refund_counter,
+            ..Default::default()
+        });
+    }
+
+    /// Discard the top checkpoint layer, undoing every write made since the matching
+    /// `checkpoint()` call and restoring the refund counter to what it was then.
+    pub fn revert_to_checkpoint(&mut self) {
+        if let Some(layer) = self.checkpoints.pop() {
+            self.refund_counter = layer.refund_snapshot;
+        }
+    }
+
+    /// Fold the top checkpoint layer into the one below it (or into `state` if it was
+    /// the outermost frame). Committing an empty layer is a cheap no-op.
+    pub fn commit_checkpoint(&mut self) {
+        let layer = match self.checkpoints.pop() {
+            Some(layer) => layer,
+            None => return,
+        };
+
+        if layer.storage.is_empty() && layer.balance.is_empty() && layer.nonce.is_empty() {
+            return;
+        }
+
+        match self.checkpoints.last_mut() {
+            Some(parent) => {
+                for (address, storage) in layer.storage {
+                    parent.storage.entry(address).or_default().extend(storage);
+                }
+                for (address, balance) in layer.balance {
+                    parent.balance.insert(address, balance);
+                }
+                for (address, nonce) in layer.nonce {
+                    parent.nonce.insert(address, nonce);
+                }
+            }
+            None => {
+                for (address, storage) in layer.storage {
+                    self.state
+                        .entry(address)
+                        .or_insert_with(Default::default)
+                        .storage
+                        .extend(storage);
+                }
+                for (address, balance) in layer.balance {
+                    self.state
+                        .entry(address)
+                        .or_insert_with(Default::default)
+                        .balance = balance;
+                }
+                for (address, nonce) in layer.nonce {
+                    self.state
+                        .entry(address)
+                        .or_insert_with(Default::default)
+                        .nonce = nonce;
+                }
+            }
+        }
+    }
+
+    /// Look up `index` in `address`'s storage through the checkpoint stack, top-down.
+    fn checkpoint_storage(&self, address: H160, index: H256) -> Option<H256> {
+        for layer in self.checkpoints.iter().rev() {
+            if let Some(value) = layer.storage.get(&address).and_then(|s| s.get(&index)) {
+                return Some(*value);
+            }
+        }
+        None
+    }
+
+    /// Look up `address`'s balance through the checkpoint stack, top-down.
+    fn checkpoint_balance(&self, address: H160) -> Option<U256> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find_map(|layer| layer.balance.get(&address).cloned())
+    }
+
+    /// Look up `address`'s nonce through the checkpoint stack, top-down.
+    fn checkpoint_nonce(&self, address: H160) -> Option<U256> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find_map(|layer| layer.nonce.get(&address).cloned())
+    }
+
+    /// The value `index` held in `address`'s storage at the start of the current
+    /// transaction, regardless of how many times it has been written since.
+    pub fn original_storage(&self, address: H160, index: H256) -> H256 {
+        self.original_storage
+            .get(&(address, index))
+            .cloned()
+            .unwrap_or_else(|| self.storage(address, index).unwrap_or_default())
+    }
+
+    /// Write `value` into `index` of `address`'s storage in the topmost checkpoint
+    /// layer (or directly into `state` if no checkpoint is open), pricing the write
+    /// per EIP-2200/1283 net gas metering and updating the refund counter.
+    pub fn sstore(&mut self, address: H160, index: H256, value: H256) -> SstoreCost {
+        let current = self.storage(address, index).unwrap_or_default();
+        self.original_storage.entry((address, index)).or_insert(current);
+
+        let original = self.original_storage(address, index);
+        let cost = Self::classify_sstore(original, current, value);
+
+        match cost {
+            SstoreCost::NoOp => {}
+            SstoreCost::InitialSet | SstoreCost::CleanUpdate => {
+                if original != H256::default() && value == H256::default() {
+                    self.refund_counter += SSTORE_CLEARS_SCHEDULE;
+                }
+            }
+            SstoreCost::DirtyUpdate => {
+                if original != H256::default() {
+                    if current == H256::default() {
+                        self.refund_counter -= SSTORE_CLEARS_SCHEDULE;
+                    }
+                    if value == H256::default() {
+                        self.refund_counter += SSTORE_CLEARS_SCHEDULE;
+                    }
+                }
+                if original == value {
+                    self.refund_counter += if original == H256::default() {
+                        (SSTORE_SET_GAS - SLOAD_GAS) as i64
+                    } else {
+                        (SSTORE_RESET_GAS - SLOAD_GAS) as i64
+                    };
+                }
+            }
+        }
+
+        match self.checkpoints.last_mut() {
+            Some(layer) => {
+                layer.storage.entry(address).or_default().insert(index, value);
+            }
+            None => {
+                self.state
+                    .entry(address)
+                    .or_insert_with(Default::default)
+                    .storage
+                    .insert(index, value);
+            }
+        }
+
+        cost
+    }
+
+    /// The gas cost, in gas units, of an `SstoreCost` tier under net gas metering.
+    pub fn sstore_gas(cost: SstoreCost) -> u64 {
+        match cost {
+            SstoreCost::NoOp | SstoreCost::DirtyUpdate => SLOAD_GAS,
+            SstoreCost::InitialSet => SSTORE_SET_GAS,
+            SstoreCost::CleanUpdate => SSTORE_RESET_GAS,
+        }
+    }
+
+    /// Write `address`'s balance in the topmost checkpoint layer (or directly into
+    /// `state` if no checkpoint is open), mirroring `sstore`'s layering so a reverted
+    /// call frame undoes balance transfers it made along the way.
+    pub fn set_balance(&mut self, address: H160, balance: U256) {
+        match self.checkpoints.last_mut() {
+            Some(layer) => {
+                layer.balance.insert(address, balance);
+            }
+            None => {
+                self.state.entry(address).or_insert_with(Default::default).balance = balance;
+            }
+        }
+    }
+
+    /// Write `address`'s nonce in the topmost checkpoint layer (or directly into
+    /// `state` if no checkpoint is open), mirroring `sstore`'s layering so a reverted
+    /// call frame undoes nonce bumps (e.g. from contract creation) it made along the
+    /// way.
+    pub fn set_nonce(&mut self, address: H160, nonce: U256) {
+        match self.checkpoints.last_mut() {
+            Some(layer) => {
+                layer.nonce.insert(address, nonce);
+            }
+            None => {
+                self.state.entry(address).or_insert_with(Default::default).nonce = nonce;
+            }
+        }
+    }
+
+    /// Secure Merkle-Patricia root over the full account set, matching the Ethereum
+    /// `stateRoot` header field: each account is RLP-encoded as
+    /// `(nonce, balance, storage_root, code_hash)` and keyed by its Keccak256 hash.
+    pub fn state_root(&self) -> H256 {
+        let entries = self.state.iter().map(|(address, account)| {
+            let storage_root = Self::storage_root(&account.storage);
+            let code_hash = H256::from_slice(Keccak256::digest(&account.code).as_slice());
+
+            let mut stream = RlpStream::new_list(4);
+            stream
+                .append(&account.nonce)
+                .append(&account.balance)
+                .append(&storage_root)
+                .append(&code_hash);
+
+            (address.as_bytes().to_vec(), stream.out().to_vec())
+        });
+
+        triehash_ethereum::sec_trie_root(entries)
+    }
+
+    /// Secure Merkle-Patricia root over a single account's storage, keyed by the
+    /// Keccak256 hash of each slot. Zero-valued slots are excluded, matching the
+    /// pruning `apply` already performs on `state`.
+    fn storage_root(storage: &BTreeMap<H256, H256>) -> H256 {
+        let entries = storage
+            .iter()
+            .filter(|(_, value)| **value != H256::default())
+            .map(|(index, value)| {
+                // Trie values are RLP-encoded as big-endian integers with leading
+                // zero bytes stripped, not as fixed 32-byte strings.
+                let mut stream = RlpStream::new();
+                stream.append(&U256::from_big_endian(value.as_bytes()));
+                (index.as_bytes().to_vec(), stream.out().to_vec())
+            });
+
+        triehash_ethereum::sec_trie_root(entries)
+    }
+
+    fn classify_sstore(original: H256, current: H256, new: H256) -> SstoreCost {
+        if current == new {
+            SstoreCost::NoOp
+        } else if original == current {
+            if original == H256::default() {
+                SstoreCost::InitialSet
+            } else {
+                SstoreCost::CleanUpdate
+            }
+        } else {
+            SstoreCost::DirtyUpdate
+        }
+    }
+
+    /// Serialize the complete backend state — `state`, `archive_state`,
+    /// `local_block_num`, `logs`, and `tx_history` — into a compact SCALE-encoded
+    /// blob. The vicinity is not included; `restore` takes it separately.
+    #[cfg(feature = "with-codec")]
+    pub fn snapshot(&self) -> Vec<u8> {
+        use parity_scale_codec::Encode;
+
+        let logs: BTreeMap<U256, Vec<EncodableLog>> = self
+            .logs
+            .iter()
+            .map(|(block, logs)| (*block, logs.iter().map(EncodableLog::from).collect()))
+            .collect();
+        let tx_history: BTreeMap<H256, EncodableTxReceipt> = self
+            .tx_history
+            .iter()
+            .map(|(hash, rec)| (*hash, EncodableTxReceipt::from(rec)))
+            .collect();
+
+        (&self.state, &self.archive_state, &self.local_block_num, logs, tx_history).encode()
+    }
+
+    /// Rebuild a `MemoryBackend` against `vicinity` from a blob produced by
+    /// `snapshot`. Per-transaction bookkeeping (checkpoints, `original_storage`, the
+    /// refund counter) is not part of the snapshot and always starts empty.
+    #[cfg(feature = "with-codec")]
+    pub fn restore(
+        vicinity: &'vicinity MemoryVicinity,
+        bytes: &[u8],
+    ) -> Result<Self, parity_scale_codec::Error> {
+        use parity_scale_codec::Decode;
+
+        let (state, archive_state, local_block_num, logs, tx_history): (
+            BTreeMap<H160, MemoryAccount>,
+            BTreeMap<U256, BTreeMap<H160, MemoryAccount>>,
+            U256,
+            BTreeMap<U256, Vec<EncodableLog>>,
+            BTreeMap<H256, EncodableTxReceipt>,
+        ) = Decode::decode(&mut &bytes[..])?;
+
+        let logs = logs
+            .into_iter()
+            .map(|(block, logs)| (block, logs.into_iter().map(Log::from).collect()))
+            .collect();
+        let tx_history = tx_history
+            .into_iter()
+            .map(|(hash, rec)| (hash, TxReceipt::from(rec)))
+            .collect();
+
+        let mut archive_by_address: BTreeMap<H160, BTreeMap<U256, MemoryAccount>> = BTreeMap::new();
+        for (block, accounts) in archive_state.iter() {
+            for (address, account) in accounts.iter() {
+                archive_by_address
+                    .entry(*address)
+                    .or_default()
+                    .insert(*block, account.clone());
+            }
+        }
+
+        Ok(Self {
+            vicinity,
+            state,
+            archive_state,
+            archive_by_address,
+            local_block_num,
+            logs,
+            tx_history,
+            checkpoints: Vec::new(),
+            original_storage: BTreeMap::new(),
+            refund_counter: 0,
+        })
+    }
+
+    /// The archived account at-or-before `block`, via `archive_by_address`'s
+    /// per-address index: O(log n) to find `address`'s own archived blocks, then
+    /// O(log k) to range downward from `block` within those — O(log n) overall,
+    /// rather than the O(k) linear scan a single `archive_state.range` would need to
+    /// find the last archived block that actually touched `address`.
+    fn archive_account_at(&self, block: U256, address: H160) -> Option<&MemoryAccount> {
+        self.archive_by_address
+            .get(&address)?
+            .range(..=block)
+            .next_back()
+            .map(|(_, account)| account)
+    }
+
+    /// `address`'s balance and nonce as of the state at-or-before `block`, falling
+    /// back to the current tip if no archived block touched `address`.
+    pub fn basic_at(&self, block: U256, address: H160) -> Basic {
+        self.archive_account_at(block, address)
+            .map(|a| Basic {
+                balance: a.balance,
+                nonce: a.nonce,
+            })
+            .unwrap_or_else(|| self.basic(address).unwrap_or_default())
+    }
+
+    /// `address`'s code as of the state at-or-before `block`, falling back to the
+    /// current tip if no archived block touched `address`.
+    pub fn code_at(&self, block: U256, address: H160) -> Vec<u8> {
+        self.archive_account_at(block, address)
+            .map(|a| a.code.clone())
+            .unwrap_or_else(|| self.code(address).unwrap_or_default())
+    }
+
+    /// `index`'s value in `address`'s storage as of the state at-or-before `block`,
+    /// falling back to the current tip if no archived block touched `address`, or if
+    /// the archived account found holds no entry for `index`.
+    pub fn storage_at(&self, block: U256, address: H160, index: H256) -> H256 {
+        self.archive_account_at(block, address)
+            .and_then(|a| a.storage.get(&index).cloned())
+            .unwrap_or_else(|| self.storage(address, index).unwrap_or_default())
+    }
+
+    /// Decode `(block_number, address, slot)` from `input` — three consecutive
+    /// 32-byte words, the middle one left-zero-padded to an `H160` — and return the
+    /// value that slot held at that block.
+    ///
+    /// This is the call logic a precompile would delegate to, not a precompile
+    /// itself: the `Precompile`/`PrecompileSet` trait and the executor's `Config`
+    /// that would register an address against this function both live outside this
+    /// crate's backend layer, so that wiring is left to the caller. Once wired up, it
+    /// lets contracts perform cross-block reads against a pinned snapshot of another
+    /// block's state (e.g. for rollup/"booster"-style execution).
+    pub fn historical_storage_precompile(&self, input: &[u8]) -> Result<[u8; 32], &'static str> {
+        if input.len() != 96 {
+            return Err("historical storage precompile expects 96 bytes of input");
+        }
+
+        let block = U256::from_big_endian(&input[0..32]);
+        let address = H160::from_slice(&input[44..64]);
+        let slot = H256::from_slice(&input[64..96]);
+
+        Ok(self.storage_at(block, address, slot).0)
+    }
 }
 
 impl<'vicinity> Backend for MemoryBackend<'vicinity> {
@@ -110,8 +653,8 @@ impl<'vicinity> Backend for MemoryBackend<'vicinity> {
     fn origin(&self) -> H160 {
         self.vicinity.origin
     }
-    fn block_hash(&self, number: U256) -> H256 {
-        if number >= self.vicinity.block_number
+    fn block_hash(&self, number: U256) -> Result<H256, BackendError> {
+        Ok(if number >= self.vicinity.block_number
             || self.vicinity.block_number - number - U256::one()
                 >= U256::from(self.vicinity.block_hashes.len())
         {
@@ -119,7 +662,7 @@ impl<'vicinity> Backend for MemoryBackend<'vicinity> {
         } else {
             let index = (self.vicinity.block_number - number - U256::one()).as_usize();
             self.vicinity.block_hashes[index]
-        }
+        })
     }
     fn block_number(&self) -> U256 {
         self.vicinity.block_number
@@ -145,14 +688,20 @@ impl<'vicinity> Backend for MemoryBackend<'vicinity> {
         self.state.contains_key(&address)
     }
 
-    fn basic(&self, address: H160) -> Basic {
-        self.state
+    fn basic(&self, address: H160) -> Result<Basic, BackendError> {
+        let committed = self
+            .state
             .get(&address)
             .map(|a| Basic {
                 balance: a.balance,
                 nonce: a.nonce,
             })
-            .unwrap_or_default()
+            .unwrap_or_default();
+
+        Ok(Basic {
+            balance: self.checkpoint_balance(address).unwrap_or(committed.balance),
+            nonce: self.checkpoint_nonce(address).unwrap_or(committed.nonce),
+        })
     }
 
     fn code_hash(&self, address: H160) -> H256 {
@@ -166,26 +715,32 @@ impl<'vicinity> Backend for MemoryBackend<'vicinity> {
         self.state.get(&address).map(|v| v.code.len()).unwrap_or(0)
     }
 
-    fn code(&self, address: H160) -> Vec<u8> {
-        self.state
+    fn code(&self, address: H160) -> Result<Vec<u8>, BackendError> {
+        Ok(self
+            .state
             .get(&address)
             .map(|v| v.code.clone())
-            .unwrap_or_default()
+            .unwrap_or_default())
     }
 
-    fn storage(&self, address: H160, index: H256) -> H256 {
-        self.state
+    fn storage(&self, address: H160, index: H256) -> Result<H256, BackendError> {
+        if let Some(value) = self.checkpoint_storage(address, index) {
+            return Ok(value);
+        }
+
+        Ok(self
+            .state
             .get(&address)
             .map(|v| v.storage.get(&index).cloned().unwrap_or(H256::default()))
-            .unwrap_or(H256::default())
+            .unwrap_or(H256::default()))
     }
 
-    fn tx_receipt(&self, hash: H256) -> TxReceipt {
-        if let Some(txrec) = self.tx_history.get(&hash) {
+    fn tx_receipt(&self, hash: H256) -> Result<TxReceipt, BackendError> {
+        Ok(if let Some(txrec) = self.tx_history.get(&hash) {
             txrec.clone()
         } else {
             TxReceipt::default()
-        }
+        })
     }
 }
 
@@ -198,7 +753,8 @@ impl<'vicinity> ApplyBackend for MemoryBackend<'vicinity> {
         recs: Vec<TxReceipt>,
         created_contracts: BTreeSet<H160>,
         delete_empty: bool,
-    ) where
+    ) -> Result<(), BackendError>
+    where
         A: IntoIterator<Item = Apply<I>>,
         I: IntoIterator<Item = (H256, H256)>,
         L: IntoIterator<Item = Log>,
@@ -291,9 +847,16 @@ impl<'vicinity> ApplyBackend for MemoryBackend<'vicinity> {
                                 }
                             }
 
-                            account.balance == U256::zero()
+                            let is_empty = account.balance == U256::zero()
                                 && account.nonce == U256::zero()
-                                && account.code.len() == 0
+                                && account.code.len() == 0;
+
+                            self.archive_by_address
+                                .entry(address)
+                                .or_default()
+                                .insert(block, account.clone());
+
+                            is_empty
                         }
                     };
 
@@ -317,5 +880,258 @@ impl<'vicinity> ApplyBackend for MemoryBackend<'vicinity> {
         for rec in recs {
             self.tx_history.insert(rec.hash, rec);
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vicinity() -> MemoryVicinity {
+        MemoryVicinity {
+            gas_price: U256::zero(),
+            origin: H160::default(),
+            chain_id: U256::one(),
+            block_hashes: Vec::new(),
+            block_number: U256::zero(),
+            block_coinbase: H160::default(),
+            block_timestamp: U256::zero(),
+            block_difficulty: U256::zero(),
+            block_gas_limit: U256::zero(),
+        }
+    }
+
+    fn h256(value: u64) -> H256 {
+        H256::from_low_u64_be(value)
+    }
+
+    #[test]
+    fn classify_sstore_matches_eip2200_table() {
+        let zero = H256::default();
+        let a = h256(1);
+        let b = h256(2);
+
+        // current == new is always a no-op, regardless of original.
+        assert_eq!(MemoryBackend::classify_sstore(zero, a, a), SstoreCost::NoOp);
+        assert_eq!(MemoryBackend::classify_sstore(a, a, a), SstoreCost::NoOp);
+
+        // original == current == 0: first write this transaction.
+        assert_eq!(MemoryBackend::classify_sstore(zero, zero, a), SstoreCost::InitialSet);
+
+        // original == current != 0: update of an untouched non-zero slot.
+        assert_eq!(MemoryBackend::classify_sstore(a, a, b), SstoreCost::CleanUpdate);
+        assert_eq!(MemoryBackend::classify_sstore(a, a, zero), SstoreCost::CleanUpdate);
+
+        // original != current: the slot was already touched this transaction.
+        assert_eq!(MemoryBackend::classify_sstore(zero, a, b), SstoreCost::DirtyUpdate);
+        assert_eq!(MemoryBackend::classify_sstore(a, b, a), SstoreCost::DirtyUpdate);
+    }
+
+    #[test]
+    fn sstore_refunds_resetting_a_dirty_slot_back_to_a_zero_original() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x11);
+        let index = h256(1);
+
+        // original is zero; first touch sets it non-zero (dirty), then a second
+        // write resets it back to the original zero value.
+        backend.sstore(address, index, h256(7));
+        backend.sstore(address, index, H256::default());
+
+        assert_eq!(
+            backend.refund_counter(),
+            (SSTORE_SET_GAS - SLOAD_GAS) as i64,
+            "resetting a dirty slot back to a zero original should refund SSTORE_SET_GAS - SLOAD_GAS"
+        );
+    }
+
+    #[test]
+    fn sstore_refunds_resetting_a_dirty_slot_back_to_a_non_zero_original() {
+        let vicinity = vicinity();
+        let mut state = BTreeMap::new();
+        let address = H160::repeat_byte(0x22);
+        let index = h256(1);
+        let mut account = MemoryAccount::default();
+        account.storage.insert(index, h256(9));
+        state.insert(address, account);
+
+        let mut backend = MemoryBackend::new(&vicinity, state);
+
+        // original is 9; dirty it to 7, then reset back to the original 9.
+        backend.sstore(address, index, h256(7));
+        backend.sstore(address, index, h256(9));
+
+        assert_eq!(
+            backend.refund_counter(),
+            (SSTORE_RESET_GAS - SLOAD_GAS) as i64,
+            "resetting a dirty slot back to a non-zero original should refund SSTORE_RESET_GAS - SLOAD_GAS"
+        );
+    }
+
+    #[test]
+    fn state_root_of_an_empty_account_set_is_the_canonical_empty_trie_root() {
+        let vicinity = vicinity();
+        let backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+
+        // The well-known Merkle-Patricia empty-trie root, independent of this
+        // crate's encoding — a known-answer check that doesn't just compare the
+        // same code against itself.
+        let empty_trie_root = H256::from_slice(
+            &hex::decode("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b42").unwrap(),
+        );
+
+        assert_eq!(backend.state_root(), empty_trie_root);
+    }
+
+    #[cfg(feature = "with-codec")]
+    #[test]
+    fn snapshot_restore_round_trip() {
+        let vicinity = vicinity();
+        let mut state = BTreeMap::new();
+        let address = H160::repeat_byte(0x33);
+        let mut account = MemoryAccount::default();
+        account.balance = U256::from(100);
+        account.nonce = U256::from(1);
+        account.storage.insert(h256(1), h256(2));
+        state.insert(address, account);
+
+        let backend = MemoryBackend::new(&vicinity, state);
+        let bytes = backend.snapshot();
+
+        let restored = MemoryBackend::restore(&vicinity, &bytes).expect("snapshot decodes");
+
+        assert_eq!(restored.state(), backend.state());
+        assert_eq!(restored.state_root(), backend.state_root());
+    }
+
+    #[test]
+    fn revert_to_checkpoint_undoes_storage_write() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x44);
+        let index = h256(1);
+
+        backend.checkpoint();
+        backend.sstore(address, index, h256(9));
+        assert_eq!(backend.storage(address, index).unwrap(), h256(9));
+
+        backend.revert_to_checkpoint();
+        assert_eq!(backend.storage(address, index).unwrap(), H256::default());
+    }
+
+    #[test]
+    fn revert_to_checkpoint_restores_the_refund_counter() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x55);
+        let index = h256(1);
+
+        // Dirty the slot, then clear it, outside of any checkpoint, earning a refund.
+        backend.sstore(address, index, h256(9));
+        backend.sstore(address, index, H256::default());
+        let refund_before = backend.refund_counter();
+        assert_ne!(refund_before, 0);
+
+        backend.checkpoint();
+        backend.sstore(address, index, h256(9));
+        backend.sstore(address, index, H256::default());
+        assert_ne!(backend.refund_counter(), refund_before);
+
+        backend.revert_to_checkpoint();
+        assert_eq!(backend.refund_counter(), refund_before);
+    }
+
+    #[test]
+    fn commit_checkpoint_folds_storage_into_the_parent_layer() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x66);
+        let index = h256(1);
+
+        backend.checkpoint(); // outer frame
+        backend.checkpoint(); // inner frame
+        backend.sstore(address, index, h256(9));
+        backend.commit_checkpoint(); // fold inner into outer
+
+        // Still visible from within the outer frame...
+        assert_eq!(backend.storage(address, index).unwrap(), h256(9));
+
+        // ...and undone entirely once the outer frame reverts, proving the write
+        // really landed in the outer layer rather than `state`.
+        backend.revert_to_checkpoint();
+        assert_eq!(backend.storage(address, index).unwrap(), H256::default());
+    }
+
+    #[test]
+    fn commit_checkpoint_folds_storage_into_state_when_outermost() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x77);
+        let index = h256(1);
+
+        backend.checkpoint();
+        backend.sstore(address, index, h256(9));
+        backend.commit_checkpoint();
+
+        assert_eq!(backend.storage(address, index).unwrap(), h256(9));
+        assert_eq!(
+            backend.state().get(&address).and_then(|a| a.storage.get(&index)).cloned(),
+            Some(h256(9))
+        );
+    }
+
+    #[test]
+    fn committing_an_empty_checkpoint_is_a_no_op() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x88);
+
+        backend.checkpoint();
+        backend.commit_checkpoint();
+
+        assert!(backend.state().get(&address).is_none());
+        let basic = backend.basic(address).unwrap();
+        assert_eq!(basic.balance, U256::zero());
+        assert_eq!(basic.nonce, U256::zero());
+    }
+
+    #[test]
+    fn set_balance_and_set_nonce_are_undone_on_revert() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0x99);
+
+        backend.checkpoint();
+        backend.set_balance(address, U256::from(100));
+        backend.set_nonce(address, U256::from(1));
+        assert_eq!(backend.basic(address).unwrap().balance, U256::from(100));
+        assert_eq!(backend.basic(address).unwrap().nonce, U256::from(1));
+
+        backend.revert_to_checkpoint();
+        let basic = backend.basic(address).unwrap();
+        assert_eq!(basic.balance, U256::zero());
+        assert_eq!(basic.nonce, U256::zero());
+    }
+
+    #[test]
+    fn set_balance_and_set_nonce_survive_a_commit() {
+        let vicinity = vicinity();
+        let mut backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let address = H160::repeat_byte(0xaa);
+
+        backend.checkpoint();
+        backend.set_balance(address, U256::from(100));
+        backend.set_nonce(address, U256::from(1));
+        backend.commit_checkpoint();
+
+        assert_eq!(backend.basic(address).unwrap().balance, U256::from(100));
+        assert_eq!(backend.basic(address).unwrap().nonce, U256::from(1));
+        assert_eq!(
+            backend.state().get(&address).map(|a| (a.balance, a.nonce)),
+            Some((U256::from(100), U256::from(1)))
+        );
     }
 }
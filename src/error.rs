@@ -0,0 +1,14 @@
+//! # Backend errors
+
+use alloc::string::String;
+
+/// Failure modes of a `Backend` whose state may come from a fallible external
+/// source, such as a JSON-RPC endpoint.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BackendError {
+    /// The configured RPC endpoint could not be reached, or returned an error
+    /// response.
+    Rpc(String),
+    /// The RPC endpoint's response could not be decoded into the expected shape.
+    Decode(String),
+}
@@ -0,0 +1,176 @@
+//! # Ethereum state-test harness
+//!
+//! Decodes the upstream `ethereum/tests` `GeneralStateTest` JSON shape, loads a
+//! fixture's `pre` state into a fresh `MemoryBackend`, and checks a backend's
+//! `state_root()` and logs root against the fixture's expected `post` values. The
+//! fixture's transaction is not executed here.
+
+#![cfg(feature = "state-tests")]
+
+use crate::backend::{Log, MemoryAccount, MemoryBackend, MemoryVicinity};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use primitive_types::{H160, H256, U256};
+use rlp::RlpStream;
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+use std::collections::BTreeMap as StdBTreeMap;
+
+/// A single pre-state account entry in a `GeneralStateTest` fixture.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestAccount {
+    pub balance: U256,
+    pub nonce: U256,
+    pub code: String,
+    pub storage: StdBTreeMap<H256, H256>,
+}
+
+/// The `env` section of a `GeneralStateTest` fixture, mapped onto `MemoryVicinity`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestEnv {
+    #[serde(rename = "currentCoinbase")]
+    pub current_coinbase: H160,
+    #[serde(rename = "currentDifficulty")]
+    pub current_difficulty: U256,
+    #[serde(rename = "currentGasLimit")]
+    pub current_gas_limit: U256,
+    #[serde(rename = "currentNumber")]
+    pub current_number: U256,
+    #[serde(rename = "currentTimestamp")]
+    pub current_timestamp: U256,
+}
+
+/// The `transaction` section of a `GeneralStateTest` fixture.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestTransaction {
+    #[serde(rename = "gasPrice")]
+    pub gas_price: U256,
+    pub nonce: U256,
+    pub to: Option<H160>,
+    pub value: Vec<U256>,
+    pub data: Vec<String>,
+    #[serde(rename = "gasLimit")]
+    pub gas_limit: Vec<U256>,
+    #[serde(rename = "secretKey")]
+    pub secret_key: H256,
+}
+
+/// A single fork's expected outcome in the `post` section of a fixture.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestPostState {
+    pub hash: H256,
+    pub logs: H256,
+    pub indexes: TestPostIndexes,
+}
+
+/// The `(data, gas, value)` index triple a `post` entry picks out of the
+/// transaction's `data`/`gasLimit`/`value` arrays.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestPostIndexes {
+    pub data: usize,
+    pub gas: usize,
+    pub value: usize,
+}
+
+/// A single decoded `GeneralStateTest` fixture (one JSON top-level value).
+#[derive(Clone, Debug, Deserialize)]
+pub struct StateTest {
+    pub env: TestEnv,
+    pub pre: StdBTreeMap<H160, TestAccount>,
+    pub post: StdBTreeMap<String, Vec<TestPostState>>,
+    pub transaction: TestTransaction,
+}
+
+/// The outcome of replaying one `post` entry against the fixture's transaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateTestOutcome {
+    pub fork: String,
+    pub state_root_matches: bool,
+    pub logs_root_matches: bool,
+}
+
+impl StateTest {
+    /// Build the `MemoryBackend` pre-state this fixture expects the transaction to
+    /// run against.
+    fn pre_state(&self) -> BTreeMap<H160, MemoryAccount> {
+        let mut state = BTreeMap::new();
+        for (address, account) in self.pre.iter() {
+            let code = hex_decode(&account.code);
+            state.insert(
+                *address,
+                MemoryAccount {
+                    nonce: account.nonce,
+                    balance: account.balance,
+                    storage: account.storage.iter().map(|(k, v)| (*k, *v)).collect(),
+                    code,
+                    created: false,
+                },
+            );
+        }
+        state
+    }
+
+    /// The `MemoryVicinity` this fixture's `env` section maps onto.
+    fn vicinity(&self) -> MemoryVicinity {
+        MemoryVicinity {
+            gas_price: self.transaction.gas_price,
+            origin: H160::default(),
+            chain_id: U256::one(),
+            block_hashes: Vec::new(),
+            block_number: self.env.current_number,
+            block_coinbase: self.env.current_coinbase,
+            block_timestamp: self.env.current_timestamp,
+            block_difficulty: self.env.current_difficulty,
+            block_gas_limit: self.env.current_gas_limit,
+        }
+    }
+
+    /// Check `backend` against every fork listed in `post`, returning one outcome
+    /// per `post` entry.
+    ///
+    /// This neither builds the backend (see `backend`) nor runs the transaction (that
+    /// needs an executor, which this crate's backend layer does not own): it only
+    /// compares `backend`'s current state root, and the logs it holds for the
+    /// fixture's `currentNumber`, against each entry's expected values.
+    pub fn check(&self, backend: &MemoryBackend) -> Vec<StateTestOutcome> {
+        let mut outcomes = Vec::new();
+        for (fork, posts) in self.post.iter() {
+            for post in posts {
+                outcomes.push(StateTestOutcome {
+                    fork: fork.clone(),
+                    state_root_matches: backend.state_root() == post.hash,
+                    logs_root_matches: logs_root(backend.logs_at(self.env.current_number))
+                        == post.logs,
+                });
+            }
+        }
+        outcomes
+    }
+
+    /// A fresh `MemoryBackend` loaded with this fixture's pre-state.
+    pub fn backend<'v>(&self, vicinity: &'v MemoryVicinity) -> MemoryBackend<'v> {
+        MemoryBackend::new(vicinity, self.pre_state())
+    }
+}
+
+/// The Ethereum `logsHash`: `keccak256(rlp([log, ...]))` where each log is RLP-encoded
+/// as `(address, topics, data)`.
+fn logs_root(logs: &[Log]) -> H256 {
+    let mut stream = RlpStream::new_list(logs.len());
+    for log in logs {
+        stream.begin_list(3);
+        stream.append(&log.address);
+        stream.begin_list(log.topics.len());
+        for topic in &log.topics {
+            stream.append(topic);
+        }
+        stream.append(&log.data);
+    }
+    H256::from_slice(Keccak256::digest(&stream.out()).as_slice())
+}
+
+/// Decode a `0x`-prefixed hex string, as used throughout the `ethereum/tests` fixtures.
+fn hex_decode(value: &str) -> Vec<u8> {
+    let trimmed = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(trimmed).unwrap_or_default()
+}